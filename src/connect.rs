@@ -1,19 +1,81 @@
 //! Connection helper.
-use std::path::Path;
+use std::{io, net::SocketAddr, path::Path, time::Duration};
 
-use tokio::net::{TcpStream, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpStream, UnixStream},
+    task::JoinSet,
+    time::{sleep_until, timeout, Instant},
+};
 
 use tungstenite::{
     error::{Error, UrlError},
-    handshake::client::Response,
+    handshake::client::{Request, Response},
     protocol::WebSocketConfig,
 };
 
 use crate::{domain, stream::MaybeTlsStream, IntoClientRequest, WebSocketStream};
 
-#[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls", feature = "openssl"))]
 use crate::Connector;
 
+/// Pulls the hostname and port to connect to out of a parsed request, defaulting the
+/// port by scheme (`80` for `ws://`, `443` for `wss://`) when the URL didn't specify
+/// one explicitly.
+fn host_port(request: &Request) -> Result<(String, u16), Error> {
+    let domain = domain(request)?;
+    let port = request
+        .uri()
+        .port_u16()
+        .or_else(|| match request.uri().scheme_str() {
+            Some("wss") => Some(443),
+            Some("ws") => Some(80),
+            _ => None,
+        })
+        .ok_or(Error::Url(UrlError::UnsupportedUrlScheme))?;
+
+    Ok((domain, port))
+}
+
+/// Runs the WebSocket client handshake over an already-established stream, instead
+/// of creating and connecting its own `TcpStream`/`UnixStream`. No TLS connector is
+/// passed, so only `ws://` requests succeed; `wss://` requests are rejected with
+/// `UrlError::UnsupportedUrlScheme` even when a TLS backend is enabled (use
+/// `connect_with_stream_and_connector()` for those). This is the shared tail end of
+/// the non-TLS `connect_async*`/`connect_unix_async*` helpers in this module, and is also useful
+/// on its own for transports those helpers can't create themselves, e.g. a
+/// SOCKS5/HTTP-CONNECT proxy tunnel, an in-memory duplex pipe for tests, or a socket
+/// that needed custom options set before connecting.
+pub async fn connect_with_stream<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = request.into_client_request()?;
+    crate::tls::client_async_tls_with_config(request, stream, config, None).await
+}
+
+/// The same as `connect_with_stream()` but the one can additionally specify a TLS
+/// connector to use. Please refer to `connect_with_stream()` for more details.
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls", feature = "openssl"))]
+pub async fn connect_with_stream_and_connector<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+    connector: Option<Connector>,
+) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = request.into_client_request()?;
+    crate::tls::client_async_tls_with_config(request, stream, config, connector).await
+}
+
 /// Connect to a given URL.
 pub async fn connect_async<R>(
     request: R,
@@ -37,17 +99,7 @@ where
     R: IntoClientRequest + Unpin,
 {
     let request = request.into_client_request()?;
-
-    let domain = domain(&request)?;
-    let port = request
-        .uri()
-        .port_u16()
-        .or_else(|| match request.uri().scheme_str() {
-            Some("wss") => Some(443),
-            Some("ws") => Some(80),
-            _ => None,
-        })
-        .ok_or(Error::Url(UrlError::UnsupportedUrlScheme))?;
+    let (domain, port) = host_port(&request)?;
 
     let addr = format!("{}:{}", domain, port);
     let try_socket = TcpStream::connect(addr).await;
@@ -57,7 +109,186 @@ where
         socket.set_nodelay(true)?;
     }
 
-    crate::tls::client_async_tls_with_config(request, socket, config, None).await
+    connect_with_stream(request, socket, config).await
+}
+
+/// Options controlling how `connect_async_with_options()` and `connect_async_tls_with_options()`
+/// establish the underlying connection, composable independently of which TLS backend
+/// (if any) performs the upgrade. The `Default` impl matches `connect_async_with_config()`'s
+/// behavior: sequential TCP connect, no timeouts, Nagle's algorithm left enabled.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConnectConfig {
+    /// The websocket configuration to use for the connection.
+    pub websocket_config: Option<WebSocketConfig>,
+    /// Disables Nagle's algorithm, i.e. calls `set_nodelay(true)`, on the winning socket.
+    pub disable_nagle: bool,
+    /// Resolves and connects using the [Happy Eyeballs (RFC 8305)](https://datatracker.ietf.org/doc/html/rfc8305)
+    /// algorithm instead of trying each resolved address strictly sequentially. This
+    /// avoids long stalls when a host publishes both `AAAA` and `A` records and one
+    /// address family is broken or slow to route.
+    pub happy_eyeballs: bool,
+    /// How long to wait for the TCP connect before giving up.
+    pub connect_timeout: Option<Duration>,
+    /// How long to wait for the TLS handshake and WebSocket upgrade before giving up.
+    pub handshake_timeout: Option<Duration>,
+}
+
+/// Turns an `Elapsed` from `tokio::time::timeout` into the same `Error::Io` shape
+/// used elsewhere in this module for connect-stage failures.
+fn timed_out(what: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::TimedOut, format!("timed out {what}")))
+}
+
+/// Establishes the TCP connection to `domain`:`port` per `options` — sequentially, or
+/// via happy eyeballs — applying `options.connect_timeout` around the attempt and
+/// `options.disable_nagle` to the winning socket.
+async fn connect_tcp(domain: &str, port: u16, options: &ConnectConfig) -> Result<TcpStream, Error> {
+    let connect = async {
+        if options.happy_eyeballs {
+            happy_eyeballs_connect(domain, port).await
+        } else {
+            TcpStream::connect((domain, port)).await.map_err(Error::Io)
+        }
+    };
+
+    let socket = match options.connect_timeout {
+        Some(duration) => timeout(duration, connect).await.map_err(|_| timed_out("connecting"))??,
+        None => connect.await?,
+    };
+
+    if options.disable_nagle {
+        socket.set_nodelay(true)?;
+    }
+
+    Ok(socket)
+}
+
+/// The same as `connect_async_with_config()` but `options` composes happy-eyeballs
+/// dual-stack connects with connect/handshake timeouts, instead of needing a separate
+/// entry point per combination. Please refer to `connect_async_with_config()` for the
+/// rest of the behavior.
+pub async fn connect_async_with_options<R>(
+    request: R,
+    options: ConnectConfig,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = request.into_client_request()?;
+    let (domain, port) = host_port(&request)?;
+    let socket = connect_tcp(&domain, port, &options).await?;
+
+    let handshake = connect_with_stream(request, socket, options.websocket_config);
+    match options.handshake_timeout {
+        Some(duration) => timeout(duration, handshake).await.map_err(|_| timed_out("performing the handshake"))?,
+        None => handshake.await,
+    }
+}
+
+/// The same as `connect_async()` but resolves and connects using the
+/// [Happy Eyeballs (RFC 8305)](https://datatracker.ietf.org/doc/html/rfc8305) algorithm
+/// instead of trying each resolved address strictly sequentially. A thin convenience
+/// wrapper around `connect_async_with_options()`; call that directly to combine happy
+/// eyeballs with timeouts or the other `ConnectConfig` fields.
+pub async fn connect_async_happy_eyeballs<R>(
+    request: R,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    connect_async_with_options(request, ConnectConfig { happy_eyeballs: true, ..Default::default() }).await
+}
+
+/// How long to wait after starting a connection attempt before racing the next
+/// address, per the [Happy Eyeballs](https://datatracker.ietf.org/doc/html/rfc8305)
+/// algorithm.
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolves `domain`, interleaves the resulting addresses by family (preferring IPv6,
+/// then IPv4, then alternating), and races staggered connection attempts against them,
+/// returning the first stream to connect successfully. All other in-flight attempts
+/// are aborted once a winner is found.
+async fn happy_eyeballs_connect(domain: &str, port: u16) -> Result<TcpStream, Error> {
+    let addrs = interleave_addrs(lookup_host((domain, port)).await.map_err(Error::Io)?.collect());
+
+    if addrs.is_empty() {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("could not resolve host `{domain}`"),
+        )));
+    }
+
+    let mut pending = addrs.into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_err = None;
+
+    if let Some(addr) = pending.next() {
+        attempts.spawn(TcpStream::connect(addr));
+    }
+    let mut next_attempt_at = sleep_until(Instant::now() + HAPPY_EYEBALLS_DELAY);
+    tokio::pin!(next_attempt_at);
+
+    loop {
+        tokio::select! {
+            Some(result) = attempts.join_next(), if !attempts.is_empty() => {
+                match result.expect("connect task panicked") {
+                    Ok(stream) => return Ok(stream),
+                    Err(err) => {
+                        last_err = Some(err);
+                        // Don't make a failed attempt wait out the rest of the stagger delay:
+                        // start the next address immediately so a quick RST doesn't cost a
+                        // full `HAPPY_EYEBALLS_DELAY` before failing over.
+                        if let Some(addr) = pending.next() {
+                            attempts.spawn(TcpStream::connect(addr));
+                        }
+                        next_attempt_at.as_mut().reset(Instant::now() + HAPPY_EYEBALLS_DELAY);
+                    }
+                }
+            }
+            () = &mut next_attempt_at, if pending.len() > 0 => {
+                if let Some(addr) = pending.next() {
+                    attempts.spawn(TcpStream::connect(addr));
+                }
+                next_attempt_at.as_mut().reset(Instant::now() + HAPPY_EYEBALLS_DELAY);
+            }
+            else => break,
+        }
+    }
+
+    Err(Error::Io(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotConnected, format!("could not connect to `{domain}`"))
+    })))
+}
+
+/// Reorders resolved addresses to interleave IPv6 and IPv4, IPv6 first, as recommended
+/// by RFC 8305 section 4.
+fn interleave_addrs(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut out = Vec::with_capacity(v6.len() + v4.len());
+
+    loop {
+        match (v6.next(), v4.next()) {
+            (Some(a), Some(b)) => {
+                out.push(a);
+                out.push(b);
+            }
+            (Some(a), None) => {
+                out.push(a);
+                out.extend(v6);
+                break;
+            }
+            (None, Some(b)) => {
+                out.push(b);
+                out.extend(v4);
+                break;
+            }
+            (None, None) => break,
+        }
+    }
+
+    out
 }
 
 /// Connect to a given URL but connect to UNIX domain socket
@@ -86,7 +317,7 @@ where
     let try_socket = UnixStream::connect(path).await;
     let socket = try_socket.map_err(Error::Io)?;
 
-    crate::tls::client_async_tls_with_config(request, socket, config, None).await
+    connect_with_stream(request, socket, config).await
 }
 
 /// The same as `connect_async()` but the one can specify a websocket configuration,
@@ -94,7 +325,15 @@ where
 /// `disable_nagle` specifies if the Nagle's algorithm must be disabled, i.e.
 /// `set_nodelay(true)`. If you don't know what the Nagle's algorithm is, better
 /// leave it to `false`.
-#[cfg(any(feature = "native-tls", feature = "__rustls-tls"))]
+///
+/// `connector` selects the TLS backend to drive the `wss://` handshake through,
+/// e.g. `Connector::NativeTls`, `Connector::Rustls`, or (with the `openssl` feature
+/// enabled) `Connector::Openssl`, so organizations pinned to an OpenSSL-based PKI or
+/// FIPS configuration can still use this entry point. Build the `Rustls` variant with
+/// `Connector::rustls_with_root_cert_source(RootCertSource::NativeCerts | WebpkiRoots |
+/// Custom(store))` to pick the root certificate source at runtime instead of being
+/// locked into whichever `rustls-tls-*` feature the binary was compiled with.
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls", feature = "openssl"))]
 pub async fn connect_async_tls_with_config<R>(
     request: R,
     config: Option<WebSocketConfig>,
@@ -105,17 +344,7 @@ where
     R: IntoClientRequest + Unpin,
 {
     let request = request.into_client_request()?;
-
-    let domain = domain(&request)?;
-    let port = request
-        .uri()
-        .port_u16()
-        .or_else(|| match request.uri().scheme_str() {
-            Some("wss") => Some(443),
-            Some("ws") => Some(80),
-            _ => None,
-        })
-        .ok_or(Error::Url(UrlError::UnsupportedUrlScheme))?;
+    let (domain, port) = host_port(&request)?;
 
     let addr = format!("{}:{}", domain, port);
     let try_socket = TcpStream::connect(addr).await;
@@ -125,5 +354,155 @@ where
         socket.set_nodelay(true)?;
     }
 
-    crate::tls::client_async_tls_with_config(request, socket, config, connector).await
+    connect_with_stream_and_connector(request, socket, config, connector).await
+}
+
+/// The same as `connect_async_tls_with_config()` but `options` composes happy-eyeballs
+/// dual-stack connects with connect/handshake timeouts, instead of needing a separate
+/// entry point per combination. Please refer to `connect_async_tls_with_config()` for
+/// the rest of the behavior.
+#[cfg(any(feature = "native-tls", feature = "__rustls-tls", feature = "openssl"))]
+pub async fn connect_async_tls_with_options<R>(
+    request: R,
+    options: ConnectConfig,
+    connector: Option<Connector>,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = request.into_client_request()?;
+    let (domain, port) = host_port(&request)?;
+    let socket = connect_tcp(&domain, port, &options).await?;
+
+    let handshake = connect_with_stream_and_connector(request, socket, options.websocket_config, connector);
+    match options.handshake_timeout {
+        Some(duration) => timeout(duration, handshake).await.map_err(|_| timed_out("performing the handshake"))?,
+        None => handshake.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn v4(last: u8) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, last)), 0)
+    }
+
+    fn v6(last: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, last)), 0)
+    }
+
+    #[test]
+    fn interleave_addrs_empty() {
+        assert_eq!(interleave_addrs(vec![]), vec![]);
+    }
+
+    #[test]
+    fn interleave_addrs_v6_only() {
+        let addrs = vec![v6(1), v6(2), v6(3)];
+        assert_eq!(interleave_addrs(addrs.clone()), addrs);
+    }
+
+    #[test]
+    fn interleave_addrs_v4_only() {
+        let addrs = vec![v4(1), v4(2), v4(3)];
+        assert_eq!(interleave_addrs(addrs.clone()), addrs);
+    }
+
+    #[test]
+    fn interleave_addrs_mixed_even() {
+        let addrs = vec![v4(1), v4(2), v6(1), v6(2)];
+        assert_eq!(interleave_addrs(addrs), vec![v6(1), v4(1), v6(2), v4(2)]);
+    }
+
+    #[test]
+    fn interleave_addrs_mixed_more_v6() {
+        let addrs = vec![v4(1), v6(1), v6(2), v6(3)];
+        assert_eq!(interleave_addrs(addrs), vec![v6(1), v4(1), v6(2), v6(3)]);
+    }
+
+    #[test]
+    fn interleave_addrs_mixed_more_v4() {
+        let addrs = vec![v4(1), v4(2), v4(3), v6(1)];
+        assert_eq!(interleave_addrs(addrs), vec![v6(1), v4(1), v4(2), v4(3)]);
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_connect_picks_the_first_address_to_accept() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        happy_eyeballs_connect("127.0.0.1", port).await.expect("should connect to the listener");
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_connect_fails_when_nothing_is_listening() {
+        // Bind to grab a free port, then drop the listener so the port is refused.
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let err = happy_eyeballs_connect("127.0.0.1", port).await.unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_connect_fails_over_immediately_after_a_refused_address() {
+        // "localhost" resolves to both `::1` and `127.0.0.1` on this machine; only the
+        // IPv4 listener is bound, so the IPv6 attempt (tried first) is refused right
+        // away. Failover to the IPv4 address should happen immediately rather than
+        // waiting out the rest of `HAPPY_EYEBALLS_DELAY`.
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+        });
+
+        let started = Instant::now();
+        happy_eyeballs_connect("localhost", port).await.expect("should fail over to the IPv4 listener");
+        assert!(
+            started.elapsed() < HAPPY_EYEBALLS_DELAY,
+            "refused address should not block on the stagger delay"
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_tcp_honors_connect_timeout() {
+        let options = ConnectConfig { connect_timeout: Some(Duration::from_millis(20)), ..Default::default() };
+        // TEST-NET-1 (RFC 5737): reserved for documentation, so nothing ever answers
+        // and nothing ever resets the connection either, i.e. the only thing that can
+        // end the attempt is the timeout itself.
+        let err = connect_tcp("192.0.2.1", 81, &options).await.unwrap_err();
+        match err {
+            Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            other => panic!("expected a timed out Io error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_async_with_options_honors_handshake_timeout() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // Accept the TCP connection but never write the handshake response, so the
+            // timeout -- not the peer -- has to end the attempt.
+            let (_socket, _) = listener.accept().await.unwrap();
+            std::future::pending::<()>().await
+        });
+
+        let options = ConnectConfig { handshake_timeout: Some(Duration::from_millis(50)), ..Default::default() };
+        let err = connect_async_with_options(format!("ws://127.0.0.1:{port}/"), options).await.unwrap_err();
+        match err {
+            Error::Io(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            other => panic!("expected a timed out Io error, got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,76 @@
+//! A stream that might be protected with TLS.
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A stream that might be protected with TLS.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum MaybeTlsStream<S> {
+    /// Unencrypted socket stream.
+    Plain(S),
+    /// Encrypted socket stream using `native-tls`.
+    #[cfg(feature = "native-tls")]
+    NativeTls(tokio_native_tls::TlsStream<S>),
+    /// Encrypted socket stream using `rustls`.
+    #[cfg(feature = "__rustls-tls")]
+    Rustls(tokio_rustls::client::TlsStream<S>),
+    /// Encrypted socket stream using `openssl`.
+    #[cfg(feature = "openssl")]
+    Openssl(tokio_openssl::SslStream<S>),
+}
+
+impl<S: Unpin + AsyncRead + AsyncWrite> AsyncRead for MaybeTlsStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "__rustls-tls")]
+            Self::Rustls(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref mut s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<S: Unpin + AsyncRead + AsyncWrite> AsyncWrite for MaybeTlsStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "__rustls-tls")]
+            Self::Rustls(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref mut s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(ref mut s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref mut s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "__rustls-tls")]
+            Self::Rustls(ref mut s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref mut s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "__rustls-tls")]
+            Self::Rustls(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "openssl")]
+            Self::Openssl(ref mut s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Connecting to a server, optionally wrapping the socket in TLS first.
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use tungstenite::{
+    error::{Error, UrlError},
+    handshake::client::Response,
+    protocol::WebSocketConfig,
+};
+
+#[cfg(feature = "native-tls")]
+use native_tls_crate::TlsConnector as NativeTlsConnector;
+#[cfg(feature = "openssl")]
+use openssl::ssl::SslConnector;
+#[cfg(feature = "__rustls-tls")]
+use std::sync::Arc;
+
+use crate::{domain, stream::MaybeTlsStream, IntoClientRequest, WebSocketStream};
+
+/// A TLS connector backend, selectable at runtime by passing it to
+/// `connect_async_tls_with_config`. `Plain` rejects `wss://` requests outright, which
+/// is only useful for callers who already know every request is `ws://`.
+#[non_exhaustive]
+#[derive(Clone)]
+pub enum Connector {
+    /// Rejects `wss://`; only plain `ws://` connections are allowed.
+    Plain,
+    /// Drives the handshake through the `native-tls` backend.
+    #[cfg(feature = "native-tls")]
+    NativeTls(NativeTlsConnector),
+    /// Drives the handshake through the `rustls` backend.
+    #[cfg(feature = "__rustls-tls")]
+    Rustls(Arc<rustls::ClientConfig>),
+    /// Drives the handshake through the `openssl` backend, for organizations that
+    /// must match their PKI or FIPS configuration to an OpenSSL-based `SslConnector`.
+    #[cfg(feature = "openssl")]
+    Openssl(SslConnector),
+}
+
+/// Where a `Connector::Rustls` should source its root certificates from, chosen at
+/// runtime instead of being pinned to whichever `rustls-tls-native-roots` or
+/// `rustls-tls-webpki-roots` Cargo feature the binary happened to be compiled with.
+#[cfg(feature = "__rustls-tls")]
+#[non_exhaustive]
+#[derive(Clone)]
+pub enum RootCertSource {
+    /// Trust the certificates found in the OS's native certificate store.
+    NativeCerts,
+    /// Trust the bundled Mozilla root program shipped via `webpki-roots`.
+    WebpkiRoots,
+    /// Trust exactly the certificates in the given store.
+    Custom(rustls::RootCertStore),
+}
+
+#[cfg(feature = "__rustls-tls")]
+impl Connector {
+    /// Builds a `Connector::Rustls` whose trust anchors come from `source`, so a
+    /// single build can fall back to bundled roots in minimal containers while
+    /// preferring system certs elsewhere, without needing a different build per
+    /// environment.
+    pub fn rustls_with_root_cert_source(source: RootCertSource) -> Result<Self, Error> {
+        let mut roots = rustls::RootCertStore::empty();
+        match source {
+            RootCertSource::NativeCerts => {
+                for cert in rustls_native_certs::load_native_certs()
+                    .map_err(Error::Io)?
+                {
+                    // Ignore certs the store can't parse rather than failing the whole load,
+                    // matching the `rustls-tls-native-roots` feature's existing behavior.
+                    let _ = roots.add(cert);
+                }
+            }
+            RootCertSource::WebpkiRoots => {
+                roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            }
+            RootCertSource::Custom(custom) => roots = custom,
+        }
+
+        let config = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+        Ok(Self::Rustls(Arc::new(config)))
+    }
+}
+
+/// Upgrades `stream` to `wss://` (or leaves it alone for `ws://`) per `connector`,
+/// then performs the WebSocket client handshake over the result.
+pub(crate) async fn client_async_tls_with_config<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+    connector: Option<Connector>,
+) -> Result<(WebSocketStream<MaybeTlsStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = request.into_client_request()?;
+    let domain = domain(&request)?;
+
+    match request.uri().scheme_str() {
+        Some("ws") => {
+            crate::client_async_with_config(request, MaybeTlsStream::Plain(stream), config).await
+        }
+        Some("wss") => match connector.unwrap_or(Connector::Plain) {
+            Connector::Plain => Err(Error::Url(UrlError::UnsupportedUrlScheme)),
+            #[cfg(feature = "native-tls")]
+            Connector::NativeTls(connector) => {
+                let stream = connector
+                    .connect(&domain, stream)
+                    .await
+                    .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                crate::client_async_with_config(request, MaybeTlsStream::NativeTls(stream), config).await
+            }
+            #[cfg(feature = "__rustls-tls")]
+            Connector::Rustls(client_config) => {
+                let server_name = rustls::pki_types::ServerName::try_from(domain.as_str())
+                    .map_err(|_| Error::Url(UrlError::UnsupportedUrlScheme))?
+                    .to_owned();
+                let stream = tokio_rustls::TlsConnector::from(client_config)
+                    .connect(server_name, stream)
+                    .await
+                    .map_err(Error::Io)?;
+                crate::client_async_with_config(request, MaybeTlsStream::Rustls(stream), config).await
+            }
+            #[cfg(feature = "openssl")]
+            Connector::Openssl(connector) => {
+                let ssl_config = connector
+                    .configure()
+                    .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                let ssl = ssl_config
+                    .into_ssl(&domain)
+                    .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                let mut stream = tokio_openssl::SslStream::new(ssl, stream)
+                    .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                std::pin::Pin::new(&mut stream)
+                    .connect()
+                    .await
+                    .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+                crate::client_async_with_config(request, MaybeTlsStream::Openssl(stream), config).await
+            }
+        },
+        _ => Err(Error::Url(UrlError::UnsupportedUrlScheme)),
+    }
+}